@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::windows::io::RawSocket;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use windows_sys::Win32::Foundation::{HANDLE, STATUS_SUCCESS};
+use windows_sys::Win32::Networking::WinSock::{WSARecv, WSASend, SOCKET, WSABUF};
+use windows_sys::Win32::System::Diagnostics::Debug::RtlNtStatusToDosError;
+use windows_sys::Win32::System::IO::OVERLAPPED_ENTRY;
+
+use crate::sys::windows::iocp::CompletionPort;
+use crate::sys::windows::overlapped::Overlapped;
+use crate::Token;
+
+/// Maximum number of completions dequeued by a single call to
+/// `GetQueuedCompletionStatusEx`.
+const COMPLETIONS_CAP: usize = 64;
+
+/// Which direction a [`PendingOp`] was submitted for.
+enum Direction {
+    Read,
+    Write,
+}
+
+/// Kernel-owned state for a single in-flight `WSARecv`/`WSASend`.
+///
+/// The buffer lives here, pinned behind the `Overlapped` it's submitted
+/// with, so its address never changes while the kernel may still be
+/// writing into (or reading out of) it.
+struct PendingOp {
+    token: Token,
+    direction: Direction,
+    buffer: Vec<u8>,
+    // The socket the op was submitted on, kept around purely so `Drop` can
+    // call `CancelIoEx` against it -- there's no way to recover a handle
+    // from an `OVERLAPPED*` alone, and `Overlapped` itself doesn't carry
+    // one.
+    socket: RawSocket,
+}
+
+/// A completion event produced by [`Completion::poll`].
+///
+/// Unlike `Event`, which only reports readiness, this carries the number of
+/// bytes the kernel actually transferred for the operation that finished,
+/// and, for a read, the buffer that was filled.
+#[derive(Debug)]
+pub struct CompletionEvent {
+    pub token: Token,
+    pub bytes_transferred: u32,
+    pub result: io::Result<()>,
+    /// The buffer originally passed to `submit_read`/`submit_write`,
+    /// truncated to `bytes_transferred` for a read that succeeded and left
+    /// untouched otherwise. Events returned by `poll` always carry `Some`.
+    pub buffer: Option<Vec<u8>>,
+}
+
+/// A proactor-style alternative to `Selector`.
+///
+/// `Selector` tells the caller a socket is *ready* and leaves the `recv`/
+/// `send` call to them; `Completion` instead takes the buffer up front via
+/// [`submit_read`](Completion::submit_read)/[`submit_write`](Completion::submit_write)
+/// and hands back a [`CompletionEvent`] with the transferred byte count once
+/// the operation has actually finished, much like an io_uring submission
+/// queue paired with a completion queue. It is built on the same I/O
+/// completion port used by `Selector`, so a socket registered with one
+/// `Completion` can't also be polled through `Selector`.
+pub struct Completion {
+    port: Arc<CompletionPort>,
+    // Keyed by the address of the `OVERLAPPED` embedded in each `Overlapped`,
+    // which is the only thing `GetQueuedCompletionStatusEx` gives back to us.
+    pending: Mutex<HashMap<usize, Pin<Box<(Overlapped, PendingOp)>>>>,
+}
+
+impl Completion {
+    pub fn new() -> io::Result<Completion> {
+        Ok(Completion {
+            port: Arc::new(CompletionPort::new(0)?),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Associate `socket` with this proactor's completion port.
+    ///
+    /// Afterwards, operations submitted for `socket` complete through
+    /// [`poll`](Completion::poll) rather than through a `Selector`.
+    pub fn register(&self, socket: RawSocket) -> io::Result<()> {
+        self.port.add_socket(socket as SOCKET)
+    }
+
+    /// Submit a read: the kernel fills `buffer` and later surfaces a
+    /// [`CompletionEvent`] for `token` carrying the number of bytes read.
+    pub fn submit_read(&self, socket: RawSocket, token: Token, buffer: Vec<u8>) -> io::Result<()> {
+        self.submit(socket, token, buffer, Direction::Read)
+    }
+
+    /// Submit a write: the kernel sends `buffer` and later surfaces a
+    /// [`CompletionEvent`] for `token` carrying the number of bytes written.
+    pub fn submit_write(&self, socket: RawSocket, token: Token, buffer: Vec<u8>) -> io::Result<()> {
+        self.submit(socket, token, buffer, Direction::Write)
+    }
+
+    fn submit(
+        &self,
+        socket: RawSocket,
+        token: Token,
+        buffer: Vec<u8>,
+        direction: Direction,
+    ) -> io::Result<()> {
+        let mut entry = Box::pin((
+            Overlapped::zeroed(),
+            PendingOp { token, direction, buffer, socket },
+        ));
+        let key = {
+            let (overlapped, _) = &*entry;
+            overlapped.as_ptr() as usize
+        };
+
+        let mut buf = WSABUF {
+            len: entry.1.buffer.len() as u32,
+            buf: entry.1.buffer.as_mut_ptr(),
+        };
+        let overlapped_ptr = {
+            let (overlapped, _) = &mut *entry.as_mut();
+            overlapped.as_mut_ptr()
+        };
+        let direction_is_read = matches!(entry.1.direction, Direction::Read);
+
+        // Inserted *before* the syscall below, not after: a completion
+        // that finishes inline (routine for loopback/buffered sockets) is
+        // posted to the port the moment `WSARecv`/`WSASend` returns
+        // success, which can race a concurrent `poll()` on another thread.
+        // If the entry weren't already in `pending` by then, that `poll()`
+        // would drain the notification, find nothing to match it against,
+        // and silently drop the token -- and the entry we then insert
+        // would be orphaned, waiting on a completion that already fired
+        // and will never come again (hanging `Drop`'s drain loop forever).
+        self.pending.lock().unwrap().insert(key, entry);
+
+        let mut bytes_transferred: u32 = 0;
+        let result = unsafe {
+            if direction_is_read {
+                let mut flags: u32 = 0;
+                WSARecv(
+                    socket as SOCKET,
+                    &mut buf,
+                    1,
+                    &mut bytes_transferred,
+                    &mut flags,
+                    overlapped_ptr,
+                    None,
+                )
+            } else {
+                WSASend(
+                    socket as SOCKET,
+                    &buf,
+                    1,
+                    &mut bytes_transferred,
+                    0,
+                    overlapped_ptr,
+                    None,
+                )
+            }
+        };
+
+        if result != 0 {
+            let err = io::Error::last_os_error();
+            // `ERROR_IO_PENDING` just means the completion will show up
+            // later on the port, exactly like a normal in-flight operation;
+            // any other error means the op never started and nothing will
+            // ever complete it, so the reservation above must be undone.
+            if err.raw_os_error() != Some(windows_sys::Win32::Foundation::ERROR_IO_PENDING as i32) {
+                self.pending.lock().unwrap().remove(&key);
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drain completions from the port, returning at most
+    /// [`COMPLETIONS_CAP`] at a time.
+    pub fn poll(&self, timeout_ms: u32) -> io::Result<Vec<CompletionEvent>> {
+        let mut completions: [MaybeUninit<OVERLAPPED_ENTRY>; COMPLETIONS_CAP] =
+            unsafe { MaybeUninit::uninit().assume_init() };
+        let results = self.port.get_many(&mut completions, timeout_ms)?;
+
+        let mut pending = self.pending.lock().unwrap();
+        let mut events = Vec::with_capacity(results.len());
+        for entry in results {
+            let key = entry.lpOverlapped as usize;
+            if let Some(op) = pending.remove(&key) {
+                // `Internal` mirrors `OVERLAPPED::Internal`, the NTSTATUS
+                // the operation completed with; a failed op (e.g. a reset
+                // connection) still posts a completion entry, typically
+                // with 0 bytes transferred, so this must be checked rather
+                // than assumed to be success.
+                let result = if entry.Internal as i32 == STATUS_SUCCESS {
+                    Ok(())
+                } else {
+                    let code = unsafe { RtlNtStatusToDosError(entry.Internal as i32) };
+                    Err(io::Error::from_raw_os_error(code as i32))
+                };
+
+                // SAFETY: the operation has completed (we just dequeued its
+                // notification from the port), so the kernel no longer
+                // holds a pointer into this buffer and it's safe to move
+                // it (and the now-irrelevant `Overlapped`) out of the pin.
+                let (_, op) = *unsafe { Pin::into_inner_unchecked(op) };
+
+                let mut buffer = op.buffer;
+                if result.is_ok() && matches!(op.direction, Direction::Read) {
+                    buffer.truncate(entry.dwNumberOfBytesTransferred as usize);
+                }
+
+                events.push(CompletionEvent {
+                    token: op.token,
+                    bytes_transferred: entry.dwNumberOfBytesTransferred,
+                    result,
+                    buffer: Some(buffer),
+                });
+            }
+        }
+        Ok(events)
+    }
+}
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.is_empty() {
+            return;
+        }
+
+        // `CancelIoEx` only *requests* cancellation: the kernel may still
+        // be writing into a cancelled op's buffer after this call returns.
+        // It needs the handle the op was issued against -- there's no way
+        // to recover that from the `OVERLAPPED*` alone -- which is why
+        // `PendingOp` carries its own `socket`.
+        for entry in pending.values() {
+            let (overlapped, op) = &**entry;
+            let _ = overlapped.cancel(op.socket as HANDLE);
+        }
+
+        // Block until every one of those cancellations (or, for an op that
+        // raced a cancel and finished anyway, its normal completion) has
+        // actually shown up on the port, so we never free a buffer the
+        // kernel might still hold a pointer into. Bounded by the number of
+        // ops outstanding, so this can't loop forever.
+        let mut remaining = pending.len();
+        while remaining > 0 {
+            let mut completions: [MaybeUninit<OVERLAPPED_ENTRY>; COMPLETIONS_CAP] =
+                unsafe { MaybeUninit::uninit().assume_init() };
+            let results = match self.port.get_many(&mut completions, u32::MAX) {
+                Ok(results) => results,
+                // The port itself is gone; nothing more can possibly
+                // complete, so further waiting would hang forever.
+                Err(_) => break,
+            };
+            for entry in results {
+                if pending.remove(&(entry.lpOverlapped as usize)).is_some() {
+                    remaining -= 1;
+                }
+            }
+        }
+    }
+}
@@ -42,6 +42,9 @@ cfg_net! {
     pub(crate) mod udp;
 
     pub use selector::{SelectorInner, SockState};
+
+    mod completion;
+    pub use completion::{Completion, CompletionEvent};
 }
 
 cfg_os_ext! {
@@ -69,17 +72,18 @@ cfg_os_proc! {
 
 cfg_io_source! {
     use std::io;
-    use std::os::windows::io::RawSocket;
+    use std::os::windows::io::{RawHandle, RawSocket};
     use std::pin::Pin;
     use std::sync::{Arc, Mutex};
 
+    use selector::HandleState;
     use crate::{Interest, Registry, Token};
 
-    struct InternalState {
-        selector: Arc<SelectorInner>,
-        token: Token,
-        interests: Interest,
-        sock_state: Pin<Arc<Mutex<SockState>>>,
+    pub(crate) struct InternalState {
+        pub(crate) selector: Arc<SelectorInner>,
+        pub(crate) token: Token,
+        pub(crate) interests: Interest,
+        pub(crate) sock_state: Pin<Arc<Mutex<SockState>>>,
     }
 
     impl Drop for InternalState {
@@ -89,17 +93,35 @@ cfg_io_source! {
         }
     }
 
+    struct InternalHandleState {
+        selector: Arc<SelectorInner>,
+        token: Token,
+        interests: Interest,
+        handle_state: Pin<Arc<Mutex<HandleState>>>,
+    }
+
+    impl Drop for InternalHandleState {
+        fn drop(&mut self) {
+            let _ = self.selector.deregister_handle(&self.handle_state);
+        }
+    }
+
     pub struct IoSourceState {
         // This is `None` if the socket has not yet been registered.
         //
         // We box the internal state to not increase the size on the stack as the
         // type might move around a lot.
         inner: Option<Box<InternalState>>,
+        // Parallel slot for sources registered by `RawHandle` (files, pipes,
+        // and other overlapped handles) rather than by `RawSocket`. `register`
+        // fills `inner` and `register_handle` fills this slot; neither checks
+        // whether the other slot is already in use.
+        inner_handle: Option<Box<InternalHandleState>>,
     }
 
     impl IoSourceState {
         pub fn new() -> IoSourceState {
-            IoSourceState { inner: None }
+            IoSourceState { inner: None, inner_handle: None }
         }
 
         pub fn do_io<T, F, R>(&self, f: F, io: &T) -> io::Result<R>
@@ -171,5 +193,59 @@ cfg_io_source! {
                 None => Err(io::ErrorKind::NotFound.into()),
             }
         }
+
+        /// Register an overlapped `HANDLE` (file, anonymous/named pipe, or
+        /// other waitable object opened with `FILE_FLAG_OVERLAPPED`), the
+        /// handle-based counterpart to [`register`](IoSourceState::register).
+        pub fn register_handle(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+            handle: RawHandle,
+        ) -> io::Result<()> {
+            if self.inner_handle.is_some() {
+                Err(io::ErrorKind::AlreadyExists.into())
+            } else {
+                let selector = registry.selector();
+                selector
+                    .register_handle(handle, token, interests)
+                    .map(|handle_state| {
+                        self.inner_handle = Some(Box::new(InternalHandleState {
+                            selector: selector.clone_inner(),
+                            token,
+                            interests,
+                            handle_state,
+                        }));
+                    })
+            }
+        }
+
+        pub fn reregister_handle(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            match self.inner_handle.as_mut() {
+                Some(state) => {
+                    registry
+                        .selector()
+                        .reregister_handle(&state.handle_state, token, interests)
+                        .map(|()| {
+                            state.token = token;
+                            state.interests = interests;
+                        })
+                }
+                None => Err(io::ErrorKind::NotFound.into()),
+            }
+        }
+
+        pub fn deregister_handle(&mut self) -> io::Result<()> {
+            match self.inner_handle.take() {
+                Some(_) => Ok(()),
+                None => Err(io::ErrorKind::NotFound.into()),
+            }
+        }
     }
 }
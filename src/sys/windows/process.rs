@@ -0,0 +1,74 @@
+use std::io;
+use std::os::windows::io::{AsRawHandle, RawHandle};
+
+use windows_sys::Win32::Foundation::HANDLE;
+
+use crate::sys::windows::selector::ProcessWaitState;
+use crate::sys::windows::AsHandlePtr;
+use crate::{Interest, Registry, Token};
+
+/// A child process `HANDLE`, pollable for exit through the normal `poll()`
+/// loop rather than a dedicated wait thread.
+///
+/// Registering it surfaces termination as a readable `Event` for the
+/// registered `Token`, mirroring how a Linux caller polls a pidfd; the
+/// exit code itself is still obtained the usual way (`GetExitCodeProcess`)
+/// once the event arrives.
+pub(crate) struct Process {
+    handle: RawHandle,
+    wait_state: Option<ProcessWaitState>,
+}
+
+impl Process {
+    pub(crate) fn new(handle: RawHandle) -> Process {
+        Process {
+            handle,
+            wait_state: None,
+        }
+    }
+
+    pub(crate) fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        if self.wait_state.is_some() {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        let state = registry
+            .selector()
+            .register_process_exit(self.as_handle_ptr(), token, interests)?;
+        self.wait_state = Some(state);
+        Ok(())
+    }
+
+    pub(crate) fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        // There's no in-place re-arm for a wait registration; re-create it
+        // under the new token/interests instead.
+        self.deregister()?;
+        self.register(registry, token, interests)
+    }
+
+    /// Stop delivering exit events. The wait callback and the `handles` map
+    /// entry it's registered under are torn down by `ProcessWaitState`'s own
+    /// `Drop` impl, which runs as soon as the `take()`n state below goes out
+    /// of scope.
+    pub(crate) fn deregister(&mut self) -> io::Result<()> {
+        match self.wait_state.take() {
+            Some(_) => Ok(()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
+
+impl AsHandlePtr for Process {
+    fn as_handle_ptr(&self) -> HANDLE {
+        self.handle as HANDLE
+    }
+}
@@ -0,0 +1,583 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::windows::io::RawHandle;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use windows_sys::Win32::Foundation::{HANDLE, INVALID_HANDLE_VALUE};
+use windows_sys::Win32::System::IO::OVERLAPPED_ENTRY;
+use windows_sys::Win32::System::Threading::{
+    RegisterWaitForSingleObject, UnregisterWaitEx, INFINITE, WT_EXECUTEONLYONCE,
+};
+
+use crate::sys::windows::afd::{Afd, AfdPollInfo};
+use crate::sys::windows::iocp::CompletionPort;
+use crate::sys::windows::{Event, Events};
+use crate::{Interest, Token};
+
+/// Maximum number of entries drained from the completion port in one pass
+/// of [`SelectorInner::select`].
+const MAX_COMPLETIONS: usize = 1024;
+
+/// A socket registered with a `Selector`, polled through AFD.
+///
+/// This is the readiness-only counterpart to the kernel-owned state a
+/// `Completion` keeps for an in-flight read or write: `SockState` never
+/// touches the socket's data, it only asks AFD to tell us when the socket
+/// becomes readable/writable/closed.
+#[derive(Debug)]
+pub struct SockState {
+    poll_info: AfdPollInfo,
+    afd: Arc<Afd>,
+    token: Token,
+    interests: Interest,
+    // Set once a poll request is outstanding, so `reregister` for the same
+    // socket doesn't submit a second one on top of it.
+    pending: bool,
+    // Set while this socket sits in `SelectorInner::pending_updates`
+    // waiting to be flushed, so repeated re-arms before the next flush
+    // collapse into a single queue entry instead of piling up.
+    queued: bool,
+    // Set by `InternalState`'s `Drop` and `IoSourceState::deregister`
+    // (via `mark_delete`); the socket is removed from the
+    // selector the next time its poll request completes rather than
+    // immediately, since AFD may already own the poll buffer.
+    delete: bool,
+    // A poll submission that failed during a batched `flush_pending_polls`,
+    // surfaced to the caller on the next `reregister` for this socket (see
+    // that function) since there's no synchronous caller to return it to
+    // at flush time.
+    poll_error: Option<io::Error>,
+}
+
+impl SockState {
+    pub(crate) fn mark_delete(&mut self) {
+        self.delete = true;
+    }
+}
+
+/// Metadata for a `RawHandle` registered directly with the completion port,
+/// rather than through AFD (sockets) or an explicit `Completion` submission.
+///
+/// This is the common piece shared by file/pipe handle registration
+/// ([`SelectorInner::register_handle`]) and process exit notifications,
+/// both of which key off a completion key dequeued from
+/// `GetQueuedCompletionStatusEx` rather than an AFD poll result.
+#[derive(Debug)]
+pub(crate) struct HandleInfo {
+    pub(crate) handle: HANDLE,
+    pub(crate) token: Token,
+    pub(crate) interests: Interest,
+}
+
+/// Kernel-owned state for a `RawHandle` registered with
+/// [`SelectorInner::register_handle`].
+///
+/// Unlike `SockState`, there is no separate poll request to submit: the
+/// handle was opened with `FILE_FLAG_OVERLAPPED`, so every overlapped I/O
+/// operation the caller issues on it completes straight to the port. The
+/// caller supplies their own `OVERLAPPED` to each `ReadFile`/`WriteFile`
+/// call, so we can't match completions by `OVERLAPPED*` the way AFD polls
+/// are matched; instead this is keyed by `key`, the completion key handed
+/// to `CreateIoCompletionPort` when the handle was associated, which the
+/// kernel echoes back (as `OVERLAPPED_ENTRY::lpCompletionKey`) on *every*
+/// completion for this handle regardless of which `OVERLAPPED` produced it.
+pub(crate) struct HandleState {
+    info: HandleInfo,
+    key: u32,
+}
+
+/// Context handed to [`process_wait_callback`] through
+/// `RegisterWaitForSingleObject`'s opaque `context` pointer: the port to
+/// post the completion to, and the completion key the `handles` table is
+/// keyed on for this registration.
+struct WaitCallbackContext {
+    port: Arc<CompletionPort>,
+    key: u32,
+}
+
+/// State backing a process-exit registration made with
+/// [`SelectorInner::register_process_exit`].
+///
+/// Owns an `Arc<SelectorInner>` so `Drop` can remove its own `handles` map
+/// entry without the caller needing to route back through a `Registry`
+/// first, the same way `InternalState`/`InternalHandleState` (in `mod.rs`)
+/// clean up their own registrations automatically. `wait_handle` is the
+/// registration returned by `RegisterWaitForSingleObject`; it must be torn
+/// down with `UnregisterWaitEx` before `callback_context` is freed,
+/// otherwise a callback already in flight on a thread pool thread could
+/// post through a dangling pointer.
+pub(crate) struct ProcessWaitState {
+    selector: Arc<SelectorInner>,
+    handle_state: Pin<Arc<Mutex<HandleState>>>,
+    wait_handle: HANDLE,
+    // Reclaimed in `Drop` once `UnregisterWaitEx` guarantees the callback
+    // will never run again.
+    callback_context: *mut WaitCallbackContext,
+}
+
+impl Drop for ProcessWaitState {
+    fn drop(&mut self) {
+        // Remove the dispatch-table entry first so a completion racing
+        // this drop can't look up state that's about to be freed.
+        let _ = self.selector.deregister_handle(&self.handle_state);
+        unsafe {
+            // Blocks until any callback already running has returned, per
+            // `UnregisterWaitEx`'s documented use of
+            // `INVALID_HANDLE_VALUE` to wait for in-flight callbacks; only
+            // after this is it safe to free `callback_context`.
+            UnregisterWaitEx(self.wait_handle, INVALID_HANDLE_VALUE);
+            drop(Box::from_raw(self.callback_context));
+        }
+    }
+}
+
+/// Callback run by a thread pool thread when the waited-on process handle
+/// becomes signalled (i.e. the process has exited). Hands the completion
+/// off to the port exactly like a real overlapped I/O completion would,
+/// so `select`'s dispatch code doesn't need a process-specific code path.
+unsafe extern "system" fn process_wait_callback(context: *mut c_void, _timed_out: u8) {
+    let context = &*(context as *const WaitCallbackContext);
+    // No real overlapped operation is involved, so there's no `OVERLAPPED*`
+    // to post; `select` dispatches this by completion key instead (see
+    // `HandleState`'s doc comment).
+    let _ = context.port.post(context.key, std::ptr::null_mut());
+}
+
+pub struct Selector {
+    inner: Arc<SelectorInner>,
+}
+
+impl Selector {
+    pub fn new() -> io::Result<Selector> {
+        CompletionPort::new(0).map(|cp| Selector {
+            inner: Arc::new(SelectorInner::new(cp)),
+        })
+    }
+
+    pub fn try_clone(&self) -> io::Result<Selector> {
+        Ok(Selector {
+            inner: self.inner.clone(),
+        })
+    }
+
+    pub fn select(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.select(events, timeout)
+    }
+
+    cfg_net! {
+        /// Submit every re-arm queued by [`reregister`](Selector::reregister)
+        /// since the last flush as a single batch, instead of waiting for
+        /// the next [`select`](Selector::select) to do it implicitly.
+        /// Useful when a caller wants re-arms in effect before going on to
+        /// do other work that doesn't itself call `select`.
+        pub fn flush(&self) {
+            self.inner.flush_pending_polls();
+        }
+    }
+
+    pub(crate) fn clone_inner(&self) -> Arc<SelectorInner> {
+        self.inner.clone()
+    }
+
+    /// Register an overlapped `HANDLE` (a file, anonymous/named pipe, or
+    /// other waitable object opened with `FILE_FLAG_OVERLAPPED`) so its
+    /// completions surface through [`select`](Selector::select) under
+    /// `token`, the same way a socket's readiness does.
+    pub(crate) fn register_handle(
+        &self,
+        handle: RawHandle,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<Pin<Arc<Mutex<HandleState>>>> {
+        self.inner.register_handle(handle, token, interests)
+    }
+
+    pub(crate) fn reregister_handle(
+        &self,
+        state: &Pin<Arc<Mutex<HandleState>>>,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        let mut state = state.lock().unwrap();
+        state.info.token = token;
+        state.info.interests = interests;
+        Ok(())
+    }
+
+    pub(crate) fn deregister_handle(&self, state: &Pin<Arc<Mutex<HandleState>>>) -> io::Result<()> {
+        self.inner.deregister_handle(state)
+    }
+
+    /// Register a process `HANDLE` so its termination is delivered as an
+    /// `Event` for `token` through [`select`](Selector::select), the
+    /// Windows analogue of polling a pidfd on Linux.
+    pub(crate) fn register_process_exit(
+        &self,
+        process: HANDLE,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<ProcessWaitState> {
+        self.inner.register_process_exit(process, token, interests)
+    }
+
+    cfg_net! {
+        pub(super) fn register(
+            &self,
+            socket: std::os::windows::io::RawSocket,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<super::InternalState> {
+            self.inner.register(socket, token, interests, self.inner.clone())
+        }
+
+        pub(super) fn reregister(
+            &self,
+            sock_state: Pin<Arc<Mutex<SockState>>>,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            self.inner.reregister(sock_state, token, interests)
+        }
+    }
+}
+
+pub(crate) struct SelectorInner {
+    cp: Arc<CompletionPort>,
+    afd: Arc<Afd>,
+    // Overlapped-handle and process-exit registrations, looked up by their
+    // IOCP completion key when a completion comes back from the port.
+    handles: Mutex<HashMap<u32, Pin<Arc<Mutex<HandleState>>>>>,
+    // Completion key 0 is reserved for AFD's own handle association, so
+    // handle/process registrations start from 1 to avoid colliding with it.
+    next_handle_key: AtomicUsize,
+    // Sockets whose `reregister` has been requested but not yet submitted
+    // to AFD, coalesced here so a socket re-armed several times per
+    // `select` pass is submitted once. Flushed by `flush_pending_polls`,
+    // which still issues one `IOCTL_AFD_POLL` per queued socket; the
+    // sockets are not combined into a single kernel submission.
+    pending_updates: Mutex<Vec<Pin<Arc<Mutex<SockState>>>>>,
+    // Sockets with an `IOCTL_AFD_POLL` currently outstanding with the
+    // kernel, keyed by the address of the `OVERLAPPED` embedded in their
+    // `AfdPollInfo` -- the only thing `GetQueuedCompletionStatusEx` hands
+    // back for an AFD completion (its `lpCompletionKey` is always AFD's
+    // own association key, not one we control, so unlike handle/process
+    // registrations these can't be matched by completion key). Mirrors how
+    // `Completion::pending` matches its own in-flight operations by
+    // `OVERLAPPED*`.
+    sock_polls: Mutex<HashMap<usize, Pin<Arc<Mutex<SockState>>>>>,
+}
+
+impl SelectorInner {
+    fn new(cp: CompletionPort) -> SelectorInner {
+        let cp = Arc::new(cp);
+        SelectorInner {
+            afd: Arc::new(Afd::new(&cp)),
+            cp,
+            handles: Mutex::new(HashMap::new()),
+            next_handle_key: AtomicUsize::new(1),
+            pending_updates: Mutex::new(Vec::new()),
+            sock_polls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn select(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        // Any re-arm queued by `reregister` since the last `select` must be
+        // in effect before we wait on the port, or a socket that became
+        // ready in between could be missed for a full timeout.
+        self.flush_pending_polls();
+
+        events.clear();
+        let timeout_ms = timeout.map_or(u32::MAX, |d| d.as_millis() as u32);
+
+        let mut completions: [MaybeUninit<OVERLAPPED_ENTRY>; MAX_COMPLETIONS] =
+            unsafe { MaybeUninit::uninit().assume_init() };
+        let results = self.cp.get_many(&mut completions, timeout_ms)?;
+
+        let handles = self.handles.lock().unwrap();
+        let mut sock_polls = self.sock_polls.lock().unwrap();
+        for entry in results {
+            // Handle/process registrations are matched by completion key
+            // (stable for every completion on a given handle, regardless
+            // of which `OVERLAPPED` produced it); AFD socket polls all
+            // share AFD's own completion key, so they're matched by
+            // `OVERLAPPED*` instead, against `sock_polls`.
+            let key = entry.lpCompletionKey as u32;
+            if let Some(state) = handles.get(&key) {
+                let state = state.lock().unwrap();
+                events.push(Event::new(state.info.token, state.info.interests));
+                continue;
+            }
+
+            let poll_key = entry.lpOverlapped as usize;
+            if let Some(sock_state) = sock_polls.remove(&poll_key) {
+                let mut state = sock_state.lock().unwrap();
+                // The poll request that just completed is the thing
+                // keeping this socket alive past `mark_delete`; now that
+                // it's done, there's nothing left to defer.
+                state.pending = false;
+                if !state.delete {
+                    let ready = state.poll_info.events();
+                    events.push(Event::new(state.token, ready));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Associate `handle` with the completion port and start tracking it
+    /// so a later completion on it is reported as an `Event` for `token`.
+    ///
+    /// The caller is responsible for issuing their own overlapped
+    /// `ReadFile`/`WriteFile`/etc calls on `handle` with their own
+    /// `OVERLAPPED` structures; this only arranges for the resulting
+    /// completions to surface through [`select`](SelectorInner::select).
+    pub(crate) fn register_handle(
+        self: &Arc<Self>,
+        handle: RawHandle,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<Pin<Arc<Mutex<HandleState>>>> {
+        let handle = handle as HANDLE;
+        let key = self.next_handle_key.fetch_add(1, Ordering::Relaxed) as u32;
+        self.cp.add_handle(key, handle)?;
+
+        let state = Arc::pin(Mutex::new(HandleState {
+            info: HandleInfo {
+                handle,
+                token,
+                interests,
+            },
+            key,
+        }));
+        self.handles.lock().unwrap().insert(key, state.clone());
+        Ok(state)
+    }
+
+    pub(crate) fn deregister_handle(&self, state: &Pin<Arc<Mutex<HandleState>>>) -> io::Result<()> {
+        let key = state.lock().unwrap().key;
+        self.handles.lock().unwrap().remove(&key);
+        Ok(())
+    }
+
+    /// See [`Selector::register_process_exit`].
+    pub(crate) fn register_process_exit(
+        self: &Arc<Self>,
+        process: HANDLE,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<ProcessWaitState> {
+        let key = self.next_handle_key.fetch_add(1, Ordering::Relaxed) as u32;
+        let handle_state = Arc::pin(Mutex::new(HandleState {
+            info: HandleInfo {
+                handle: process,
+                token,
+                interests,
+            },
+            key,
+        }));
+        self.handles.lock().unwrap().insert(key, handle_state.clone());
+
+        let callback_context = Box::into_raw(Box::new(WaitCallbackContext {
+            port: self.cp.clone(),
+            key,
+        }));
+
+        let mut wait_handle: HANDLE = std::ptr::null_mut();
+        let ok = unsafe {
+            RegisterWaitForSingleObject(
+                &mut wait_handle,
+                process,
+                Some(process_wait_callback),
+                callback_context as *mut c_void,
+                INFINITE,
+                WT_EXECUTEONLYONCE,
+            )
+        };
+        if ok == 0 {
+            self.handles.lock().unwrap().remove(&key);
+            unsafe { drop(Box::from_raw(callback_context)) };
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(ProcessWaitState {
+            selector: self.clone(),
+            handle_state,
+            wait_handle,
+            callback_context,
+        })
+    }
+
+    cfg_net! {
+        fn register(
+            self: &Arc<Self>,
+            socket: std::os::windows::io::RawSocket,
+            token: Token,
+            interests: Interest,
+            selector: Arc<SelectorInner>,
+        ) -> io::Result<super::InternalState> {
+            self.cp.add_socket(socket)?;
+            let sock_state = Arc::pin(Mutex::new(SockState {
+                poll_info: AfdPollInfo::zeroed(),
+                afd: self.afd.clone(),
+                token,
+                interests,
+                pending: false,
+                queued: false,
+                delete: false,
+                poll_error: None,
+            }));
+            self.reregister(sock_state.clone(), token, interests)?;
+            Ok(super::InternalState {
+                selector,
+                token,
+                interests,
+                sock_state,
+            })
+        }
+
+        fn reregister(
+            &self,
+            sock_state: Pin<Arc<Mutex<SockState>>>,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            let mut state = sock_state.lock().unwrap();
+            state.token = token;
+            state.interests = interests;
+            // A previous batched flush failed to submit a poll for this
+            // socket; surface that failure now, to the first caller able
+            // to observe it synchronously, the same way an immediate
+            // (unbatched) submission failure used to be returned directly.
+            if let Some(err) = state.poll_error.take() {
+                return Err(err);
+            }
+            // Already queued for (or has an outstanding) poll request: the
+            // token/interests update above will be picked up whenever that
+            // request is submitted or next re-armed, so there's nothing
+            // further to do.
+            if state.delete || state.queued || state.pending {
+                return Ok(());
+            }
+            state.queued = true;
+            drop(state);
+            self.pending_updates.lock().unwrap().push(sock_state);
+            Ok(())
+        }
+
+        /// Submit every socket queued by `reregister` since the last flush
+        /// as a single pass over the batch, instead of one AFD ioctl per
+        /// `reregister` call. A submission failure is stored on the
+        /// socket's `SockState` rather than discarded, so the next
+        /// `reregister` for that socket (driven by `IoSourceState::do_io`
+        /// on its next `WouldBlock`) returns it to the caller.
+        fn flush_pending_polls(&self) {
+            let batch = std::mem::take(&mut *self.pending_updates.lock().unwrap());
+            for sock_state in batch {
+                let mut state = sock_state.lock().unwrap();
+                state.queued = false;
+                // Dropped or already re-armed by the time we got to it;
+                // submitting a poll for it now would be redundant (already
+                // pending) or pointless (socket is gone).
+                if state.delete || state.pending {
+                    continue;
+                }
+                state.pending = true;
+                let interests = state.interests;
+                // Taken before `poll` is submitted: once it returns `Ok`,
+                // the kernel may post the completion at any time, so the
+                // key must already be resolvable by the time we let go of
+                // the lock.
+                let poll_key = &state.poll_info as *const AfdPollInfo as usize;
+                match self.afd.poll(&mut state.poll_info, interests) {
+                    Ok(()) => {
+                        drop(state);
+                        self.sock_polls.lock().unwrap().insert(poll_key, sock_state);
+                    }
+                    Err(err) => {
+                        state.pending = false;
+                        state.poll_error = Some(err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+cfg_net! {
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn sock_state(afd: Arc<Afd>) -> Pin<Arc<Mutex<SockState>>> {
+            Arc::pin(Mutex::new(SockState {
+                poll_info: AfdPollInfo::zeroed(),
+                afd,
+                token: Token(0),
+                interests: Interest::READABLE,
+                pending: false,
+                queued: false,
+                delete: false,
+                poll_error: None,
+            }))
+        }
+
+        #[test]
+        fn reregister_dedups_repeated_rearms() {
+            let selector = Selector::new().unwrap();
+            let state = sock_state(selector.inner.afd.clone());
+
+            selector
+                .inner
+                .reregister(state.clone(), Token(1), Interest::READABLE)
+                .unwrap();
+            selector
+                .inner
+                .reregister(state.clone(), Token(2), Interest::WRITABLE)
+                .unwrap();
+
+            // Both re-arms before any flush must collapse into a single
+            // queue entry, not one per `reregister` call.
+            assert_eq!(selector.inner.pending_updates.lock().unwrap().len(), 1);
+            // The latest token/interests still win even though the
+            // submission itself was deduplicated.
+            assert_eq!(state.lock().unwrap().token, Token(2));
+        }
+
+        #[test]
+        fn flush_never_submits_a_deleted_socket() {
+            let selector = Selector::new().unwrap();
+            let state = sock_state(selector.inner.afd.clone());
+
+            selector
+                .inner
+                .reregister(state.clone(), Token(1), Interest::READABLE)
+                .unwrap();
+            state.lock().unwrap().mark_delete();
+            selector.inner.flush_pending_polls();
+
+            let guard = state.lock().unwrap();
+            assert!(!guard.queued);
+            assert!(!guard.pending);
+        }
+
+        #[test]
+        fn flush_clears_the_queued_flag() {
+            let selector = Selector::new().unwrap();
+            let state = sock_state(selector.inner.afd.clone());
+
+            selector
+                .inner
+                .reregister(state.clone(), Token(1), Interest::READABLE)
+                .unwrap();
+            assert!(state.lock().unwrap().queued);
+
+            selector.inner.flush_pending_polls();
+            assert!(!state.lock().unwrap().queued);
+        }
+    }
+}